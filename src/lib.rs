@@ -1,18 +1,61 @@
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeBounds, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
+
+/// Byte offsets of the start of each line in a source string, built once so
+/// line/column lookups are a binary search instead of a rescan.
+fn compute_line_starts(string: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    starts.push(0);
+    starts.extend(string.match_indices('\n').map(|(i, _)| i + 1));
+    starts
+}
+
+/// 1-based line number containing the given byte index.
+fn line_at(line_starts: &[usize], index: usize) -> usize {
+    line_starts.partition_point(|&start| start <= index)
+}
+
+/// 1-based column of the given byte index within its line.
+fn column_at(line_starts: &[usize], index: usize) -> usize {
+    let line = line_at(line_starts, index);
+    index - line_starts[line - 1] + 1
+}
+
+/// Byte range `[start, end)` of the given 1-based line, excluding its trailing `\n`.
+fn line_bounds(line_starts: &[usize], string: &str, line: usize) -> (usize, usize) {
+    let start = line_starts[line - 1];
+    let end = line_starts
+        .get(line)
+        .map(|&next| next - 1)
+        .unwrap_or(string.len());
+    (start, end)
+}
 
 #[derive(Clone, Copy, PartialEq)]
 pub struct Input<'a> {
     string: &'a str,
     index: usize,
     line: usize,
+    line_starts: &'a [usize],
 }
 
 impl<'a> Input<'a> {
-    pub fn new(string: &'a str) -> Self {
+    /// Precompute the line-start table for `string`, to be passed to
+    /// [`Input::new`]. Kept separate from `Input` construction so the table
+    /// can be owned by the caller (a local variable, a field on a longer-lived
+    /// parser) instead of `Input` leaking it on every call.
+    pub fn line_starts(string: &str) -> Vec<usize> {
+        compute_line_starts(string)
+    }
+
+    /// Build a cursor over `string`, borrowing a `line_starts` table built by
+    /// [`Input::line_starts`]. The caller must keep that table alive for as
+    /// long as the `Input`.
+    pub fn new(string: &'a str, line_starts: &'a [usize]) -> Self {
         Self {
             string,
             index: 0,
             line: 1,
+            line_starts,
         }
     }
 
@@ -24,19 +67,143 @@ impl<'a> Input<'a> {
         let curr = self.curr();
         let rest = exact.exact(curr)?;
         let delta = curr.len() - rest.len();
-        let interim = &curr[..delta];
-        let newlines = interim.chars().fold(0, |acc, c| if c == '\n' {
-            acc + 1
-        } else {
-            acc
-        });
+        let index = self.index + delta;
+        let line = line_at(self.line_starts, index);
         let input = Self {
             string: self.string,
-            index: self.index + delta,
-            line: self.line + newlines,
+            index,
+            line,
+            line_starts: self.line_starts,
         };
         Some((input, ()))
     }
+
+    /// Build the `Span` this cursor covers up to `end`, checking that both
+    /// cursors point into the same source and that `self` comes no later than
+    /// `end`. Returns `None` otherwise.
+    pub fn span_to(&self, end: &Input<'a>) -> Option<Span<'a>> {
+        if !std::ptr::eq(self.string, end.string) || self.index > end.index {
+            return None;
+        }
+        Some(self.span_to_unchecked(end))
+    }
+
+    /// Like [`Input::span_to`], but skips the same-source and ordering
+    /// checks. Only use this when `end` is known to come from the same
+    /// parse as `self`.
+    pub fn span_to_unchecked(&self, end: &Input<'a>) -> Span<'a> {
+        debug_assert!(self.string.get(self.index..end.index).is_some());
+        Span {
+            string: self.string,
+            start: self.index,
+            end: end.index,
+            line: self.line,
+            line_starts: self.line_starts,
+        }
+    }
+
+    /// Advance past all leading chars matching `predicate`.
+    pub fn skip_while<F: Fn(char) -> bool>(&self, predicate: F) -> Self {
+        let curr = self.curr();
+        let mut end = curr.len();
+        for (i, c) in curr.char_indices() {
+            if !predicate(c) {
+                end = i;
+                break;
+            }
+        }
+        let index = self.index + end;
+        let line = line_at(self.line_starts, index);
+        Self {
+            string: self.string,
+            index,
+            line,
+            line_starts: self.line_starts,
+        }
+    }
+
+    /// If this cursor sits at the start of a `//` or `/* */` comment, consume
+    /// it and return the cursor past it, the `Span` it covers, and its
+    /// `CommentKind`. Returns `None` if there's no comment here, and
+    /// `Some(Err(_))` if a block comment is left unterminated.
+    pub fn comment(&self) -> Option<Result<(Self, Span<'a>, CommentKind), String>> {
+        let curr = self.curr();
+        if curr.starts_with("//") {
+            let len = curr.find('\n').unwrap_or(curr.len());
+            let end = self.advanced_by(len);
+            let span = self.span_to_unchecked(&end);
+            return Some(Ok((end, span, CommentKind::Line)));
+        }
+        if curr.starts_with("/*") {
+            let mut depth = 1usize;
+            let mut i = 2;
+            loop {
+                if i >= curr.len() {
+                    let end = self.advanced_by(curr.len());
+                    let span = self.span_to_unchecked(&end);
+                    return Some(Err(span.error("unterminated block comment")));
+                } else if curr[i..].starts_with("/*") {
+                    depth += 1;
+                    i += 2;
+                } else if curr[i..].starts_with("*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += curr[i..].chars().next().expect("i < curr.len()").len_utf8();
+                }
+            }
+            let end = self.advanced_by(i);
+            let span = self.span_to_unchecked(&end);
+            return Some(Ok((end, span, CommentKind::Block)));
+        }
+        None
+    }
+
+    /// Skip runs of whitespace and line/block comments. Fails if a block
+    /// comment is left unterminated, so a tokenizer built on this doesn't
+    /// silently stop at a stray `/*` and report a confusing error from
+    /// whatever token matching sees next.
+    pub fn skip_trivia(&self) -> Result<Self, String> {
+        let mut input = *self;
+        loop {
+            let next = input.skip_while(char::is_whitespace);
+            match next.comment() {
+                Some(Ok((after, _, _))) => input = after,
+                Some(Err(message)) => return Err(message),
+                None => {
+                    input = next;
+                    break;
+                }
+            }
+        }
+        Ok(input)
+    }
+
+    /// This cursor advanced `delta` bytes into its own string, with no
+    /// further validation. Callers must ensure `delta` lands on a char
+    /// boundary.
+    fn advanced_by(&self, delta: usize) -> Self {
+        let index = self.index + delta;
+        let line = line_at(self.line_starts, index);
+        Self {
+            string: self.string,
+            index,
+            line,
+            line_starts: self.line_starts,
+        }
+    }
+}
+
+/// The shape of a comment, mirroring rust-analyzer's `CommentKind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    /// A `//` line comment, running to the end of the line.
+    Line,
+    /// A `/* */` block comment, which may nest.
+    Block,
 }
 
 impl<'a> std::fmt::Debug for Input<'a> {
@@ -52,8 +219,17 @@ fn test_debug_input() {
     let string = "word\nword\nword";
     let index = 5;
     let line = 2;
-    let input = Input { string, index, line };
-    assert_eq!(format!("{:?}", &input), "Input(5 (2) \"word\\nword\")".to_string());
+    let line_starts = compute_line_starts(string);
+    let input = Input {
+        string,
+        index,
+        line,
+        line_starts: &line_starts,
+    };
+    assert_eq!(
+        format!("{:?}", &input),
+        "Input(5 (2) \"word\\nword\")".to_string()
+    );
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -62,6 +238,7 @@ pub struct Span<'a> {
     start: usize,
     end: usize,
     line: usize,
+    line_starts: &'a [usize],
 }
 
 impl<'a> Span<'a> {
@@ -70,31 +247,83 @@ impl<'a> Span<'a> {
     }
 
     pub fn column(&self) -> usize {
-        let string = &self.string[..self.start];
-        let index = string.rfind('\n').map(|i| i + 1).unwrap_or(0);
-        self.start - index + 1
+        column_at(self.line_starts, self.start)
+    }
+
+    /// Byte offset of the start of the span.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset of the end of the span.
+    pub fn end(&self) -> usize {
+        self.end
     }
-    
+
+    /// Merge two spans from the same source into the minimal span covering
+    /// both. Returns `None` if the spans come from different sources.
+    pub fn join(&self, other: &Span<'a>) -> Option<Span<'a>> {
+        if !std::ptr::eq(self.string, other.string) {
+            return None;
+        }
+        let (start, line) = if self.start <= other.start {
+            (self.start, self.line)
+        } else {
+            (other.start, other.line)
+        };
+        let end = self.end.max(other.end);
+        Some(Span {
+            string: self.string,
+            start,
+            end,
+            line,
+            line_starts: self.line_starts,
+        })
+    }
+
+    /// Render a rustc-style diagnostic: a header with line/column, followed by
+    /// every source line the span touches, each under a right-aligned
+    /// line-number gutter with the covered portion underlined. Single-line
+    /// spans are the degenerate case of the same loop.
     pub fn error(&self, message: &str) -> String {
-        let start = self.string
-            .get(..self.start)
-            .unwrap()
-            .rfind('\n')
-            .map(|i| i + 1)
-            .unwrap_or(0);
-        let end = self.string
-            .get(self.end..)
-            .unwrap()
-            .find('\n')
-            .unwrap_or(self.string.len());
+        let last_byte = if self.end > self.start {
+            self.end - 1
+        } else {
+            self.start
+        };
+        let end_line = line_at(self.line_starts, last_byte);
+        let width = end_line.to_string().len();
+        let gutter_pad = " ".repeat(width + 3);
+
+        let body = (self.line..=end_line)
+            .map(|line| {
+                let (line_start, line_end) = line_bounds(self.line_starts, self.string, line);
+                let content = &self.string[line_start..line_end];
+                let underline_start = if line == self.line {
+                    self.start - line_start
+                } else {
+                    0
+                };
+                let underline_end = if line == end_line {
+                    self.end.min(line_end) - line_start
+                } else {
+                    content.len()
+                };
+                let leading = content[..underline_start].chars().count();
+                let carets = content[underline_start..underline_end].chars().count();
+                format!(
+                    "{line:>width$} | {content}\n{gutter_pad}{leading}{carets}",
+                    leading = " ".repeat(leading),
+                    carets = "^".repeat(carets),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
         format!(
-            "[Error {line}:{column}] {message}\n{content}\n{leading}{carets}",
-            line    = self.line,
-            column  = self.column(),
-            message = message,
-            content = &self.string[start..end],
-            leading = " ".repeat(self.column() - 1),
-            carets  = "^".repeat(self.end - self.start),
+            "[Error {line}:{column}] {message}\n{body}",
+            line = self.line,
+            column = self.column(),
         )
     }
 }
@@ -102,7 +331,13 @@ impl<'a> Span<'a> {
 impl<'a> std::fmt::Debug for Span<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let slice = self.slice();
-        write!(f, "Span({:?} ({}) {:?})", self.start..self.end, self.line, slice)
+        write!(
+            f,
+            "Span({:?} ({}) {:?})",
+            self.start..self.end,
+            self.line,
+            slice
+        )
     }
 }
 
@@ -112,8 +347,94 @@ fn test_debug_span() {
     let start = 5;
     let end = 9;
     let line = 2;
-    let span = Span { string, start, end, line };
-    assert_eq!(format!("{:?}", span), "Span(5..9 (2) \"word\")".to_string());
+    let line_starts = compute_line_starts(string);
+    let span = Span {
+        string,
+        start,
+        end,
+        line,
+        line_starts: &line_starts,
+    };
+    assert_eq!(
+        format!("{:?}", span),
+        "Span(5..9 (2) \"word\")".to_string()
+    );
+}
+
+#[test]
+fn test_error_single_line() {
+    let string = "let x = 1;\nlet y = bad;\n";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (after_bad, ()) = {
+        let (after_let_y_eq, ()) = input.exact("let x = 1;\nlet y = ").unwrap();
+        after_let_y_eq.exact("bad").unwrap()
+    };
+    let start = input.exact("let x = 1;\nlet y = ").unwrap().0;
+    let span = start.span_to(&after_bad).unwrap();
+    assert_eq!(
+        span.error("undefined variable"),
+        "[Error 2:9] undefined variable\n2 | let y = bad;\n            ^^^"
+    );
+}
+
+#[test]
+fn test_error_multi_line() {
+    let string = "fn f() {\n    bad\n}\n";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (start, ()) = input.exact("fn f() {\n    ").unwrap();
+    let (end, ()) = start.exact("bad\n}").unwrap();
+    let span = start.span_to(&end).unwrap();
+    assert_eq!(
+        span.error("unexpected token"),
+        "[Error 2:5] unexpected token\n2 |     bad\n        ^^^\n3 | }\n    ^"
+    );
+}
+
+#[test]
+fn test_error_multibyte() {
+    let string = "café bad\n";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (start, ()) = input.exact("café ").unwrap();
+    let (end, ()) = start.exact("bad").unwrap();
+    let span = start.span_to(&end).unwrap();
+    assert_eq!(
+        span.error("bad token"),
+        "[Error 1:7] bad token\n1 | café bad\n         ^^^"
+    );
+}
+
+#[test]
+fn test_span_to() {
+    let string = "Hello, world!";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (after_hello, ()) = input.exact("Hello").unwrap();
+    let span = input.span_to(&after_hello).unwrap();
+    assert_eq!(span.slice(), "Hello");
+
+    let other_string = String::from("Hello, world!");
+    let other_line_starts = Input::line_starts(&other_string);
+    let other = Input::new(&other_string, &other_line_starts);
+    assert_eq!(input.span_to(&other), None);
+    assert_eq!(after_hello.span_to(&input), None);
+}
+
+#[test]
+fn test_span_join() {
+    let string = "Hello, world!";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (after_hello, ()) = input.exact("Hello").unwrap();
+    let (after_comma_space, ()) = after_hello.exact(", ").unwrap();
+    let (after_world, ()) = after_comma_space.exact("world").unwrap();
+
+    let hello = input.span_to(&after_hello).unwrap();
+    let world = after_comma_space.span_to(&after_world).unwrap();
+    let joined = hello.join(&world).unwrap();
+    assert_eq!(joined.slice(), "Hello, world");
 }
 
 pub trait Exact {
@@ -134,22 +455,91 @@ impl Exact for &str {
     }
 }
 
-/// Parse *any* of the characters in the inclusive range
+/// Shared body for every `RangeBounds<char>` impl below: match the next char
+/// if it falls inside the range. A single blanket `impl<R: RangeBounds<char>>
+/// Exact for R` would conflict with the `Fn(char) -> bool` blanket impl below
+/// (nothing stops a type from implementing both), so each range kind gets its
+/// own impl calling into this helper instead.
+fn exact_range<'a, R: RangeBounds<char>>(range: &R, input: &'a str) -> Option<&'a str> {
+    let c = input.chars().next()?;
+    if range.contains(&c) {
+        Some(&input[c.len_utf8()..])
+    } else {
+        None
+    }
+}
+
+/// Parse any one character in the inclusive range
 impl Exact for RangeInclusive<char> {
     fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
-        let c = input.chars().next()?;
-        if self.contains(&c) {
-            Some(&input[c.len_utf8()..])
-        } else {
-            None
-        }
+        exact_range(self, input)
+    }
+}
+
+/// Parse any one character in the half-open range
+impl Exact for Range<char> {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_range(self, input)
+    }
+}
+
+/// Parse any one character at or after the start of the range
+impl Exact for RangeFrom<char> {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_range(self, input)
+    }
+}
+
+/// Parse any one character before the end of the range
+impl Exact for RangeTo<char> {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_range(self, input)
+    }
+}
+
+/// Parse any one character at or before the end of the range
+impl Exact for RangeToInclusive<char> {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_range(self, input)
+    }
+}
+
+/// Parse any one character
+impl Exact for RangeFull {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_range(self, input)
+    }
+}
+
+/// Shared body for the char-set impls below: match the next char if it's a
+/// member of `set`.
+fn exact_char_set<'a>(set: &[char], input: &'a str) -> Option<&'a str> {
+    let c = input.chars().next()?;
+    if set.contains(&c) {
+        Some(&input[c.len_utf8()..])
+    } else {
+        None
+    }
+}
+
+/// Parse one character that is a member of the character set
+impl Exact for &[char] {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_char_set(self, input)
+    }
+}
+
+/// Parse one character that is a member of the character set
+impl<const N: usize> Exact for [char; N] {
+    fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
+        exact_char_set(self, input)
     }
 }
 
 /// Parse a single character matching the predicate
 impl<F> Exact for F
 where
-    F: Fn(char) -> bool
+    F: Fn(char) -> bool,
 {
     fn exact<'a>(&self, input: &'a str) -> Option<&'a str> {
         input.strip_prefix(self)
@@ -158,18 +548,109 @@ where
 
 #[test]
 fn test_exact() {
-    let input = Input::new("1234");
+    let line_starts = Input::line_starts("1234");
+    let input = Input::new("1234", &line_starts);
     let mut out = input;
     out.index += 1;
     assert_eq!(input.exact('0'..='9'), Some((out, ())));
 
-    let input = Input::new("Hello");
+    let line_starts = Input::line_starts("Hello");
+    let input = Input::new("Hello", &line_starts);
     let mut out = input;
     out.index += 1;
     assert_eq!(input.exact('H'), Some((out, ())));
 
-    let input = Input::new("Hello");
+    let line_starts = Input::line_starts("Hello");
+    let input = Input::new("Hello", &line_starts);
     let mut out = input;
     out.index += 5;
     assert_eq!(input.exact("Hello"), Some((out, ())));
 }
+
+#[test]
+fn test_exact_ranges_and_sets() {
+    let line_starts = Input::line_starts("abc");
+    let input = Input::new("abc", &line_starts);
+    let mut out = input;
+    out.index += 1;
+    assert_eq!(input.exact('a'..'c'), Some((out, ())));
+    assert_eq!(input.exact('a'..), Some((out, ())));
+    assert_eq!(input.exact(..'c'), Some((out, ())));
+    assert_eq!(input.exact(..='a'), Some((out, ())));
+    assert_eq!(input.exact(..), Some((out, ())));
+    assert_eq!(input.exact(['a', 'b', 'c']), Some((out, ())));
+    assert_eq!(input.exact(['a', 'b', 'c'].as_slice()), Some((out, ())));
+
+    assert_eq!(input.exact('b'..'c'), None);
+    assert_eq!(input.exact('b'..), None);
+    assert_eq!(input.exact(..'a'), None);
+}
+
+#[test]
+fn test_skip_while() {
+    let line_starts = Input::line_starts("   abc");
+    let input = Input::new("   abc", &line_starts);
+    let skipped = input.skip_while(char::is_whitespace);
+    assert_eq!(skipped.curr(), "abc");
+    assert_eq!(skipped.index, 3);
+
+    let line_starts = Input::line_starts("abc");
+    let input = Input::new("abc", &line_starts);
+    let skipped = input.skip_while(char::is_whitespace);
+    assert_eq!(skipped.index, 0);
+}
+
+#[test]
+fn test_comment_line() {
+    let line_starts = Input::line_starts("// hello\nrest");
+    let input = Input::new("// hello\nrest", &line_starts);
+    let (end, span, kind) = input.comment().unwrap().unwrap();
+    assert_eq!(kind, CommentKind::Line);
+    assert_eq!(span.slice(), "// hello");
+    assert_eq!(end.curr(), "\nrest");
+}
+
+#[test]
+fn test_comment_block_nested() {
+    let string = "/* outer /* inner */ still outer */rest";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let (end, span, kind) = input.comment().unwrap().unwrap();
+    assert_eq!(kind, CommentKind::Block);
+    assert_eq!(span.slice(), "/* outer /* inner */ still outer */");
+    assert_eq!(end.curr(), "rest");
+}
+
+#[test]
+fn test_comment_block_unterminated() {
+    let line_starts = Input::line_starts("/* oops");
+    let input = Input::new("/* oops", &line_starts);
+    let err = input.comment().unwrap().unwrap_err();
+    assert!(err.contains("unterminated block comment"), "{err}");
+}
+
+#[test]
+fn test_comment_none() {
+    let line_starts = Input::line_starts("not a comment");
+    let input = Input::new("not a comment", &line_starts);
+    assert_eq!(input.comment(), None);
+}
+
+#[test]
+fn test_skip_trivia() {
+    let string = "  // comment\n  /* block */  code";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let skipped = input.skip_trivia().unwrap();
+    assert_eq!(skipped.curr(), "code");
+}
+
+#[test]
+fn test_skip_trivia_unterminated_block_comment() {
+    let string = "  /* oops";
+    let line_starts = Input::line_starts(string);
+    let input = Input::new(string, &line_starts);
+    let err = input.skip_trivia().unwrap_err();
+    assert!(err.contains("unterminated block comment"), "{err}");
+}
+